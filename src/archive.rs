@@ -0,0 +1,198 @@
+use async_compression::tokio::bufread::{BzDecoder, GzipDecoder, XzDecoder, ZstdDecoder};
+use std::fs::{self, File};
+use std::io::copy;
+use std::path::Path;
+use tempfile::TempDir;
+use tokio::io::BufReader as TokioBufReader;
+use tokio_tar::Archive as TarArchive;
+use zip::ZipArchive;
+
+/// Container formats a FIR's sector package can be published in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+    TarBz2,
+    TarXz,
+    TarZst,
+    SevenZip,
+}
+
+impl ArchiveFormat {
+    /// Extensions `get_sector_link` accepts, tried against the listing page
+    /// in this order.
+    pub fn extensions() -> &'static [&'static str] {
+        &["zip", "tar.gz", "tgz", "tar.bz2", "tar.xz", "tar.zst", "7z"]
+    }
+
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let lower = file_name.to_lowercase();
+        if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if lower.ends_with(".tar.bz2") {
+            Some(ArchiveFormat::TarBz2)
+        } else if lower.ends_with(".tar.xz") {
+            Some(ArchiveFormat::TarXz)
+        } else if lower.ends_with(".tar.zst") {
+            Some(ArchiveFormat::TarZst)
+        } else if lower.ends_with(".7z") {
+            Some(ArchiveFormat::SevenZip)
+        } else if lower.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarBz2 => "tar.bz2",
+            ArchiveFormat::TarXz => "tar.xz",
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::SevenZip => "7z",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_file_name_matches_known_extensions() {
+        assert_eq!(
+            ArchiveFormat::from_file_name("EGLL.zip"),
+            Some(ArchiveFormat::Zip)
+        );
+        assert_eq!(
+            ArchiveFormat::from_file_name("EGLL.tar.gz"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_file_name("EGLL.tgz"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_file_name("EGLL.tar.bz2"),
+            Some(ArchiveFormat::TarBz2)
+        );
+        assert_eq!(
+            ArchiveFormat::from_file_name("EGLL.tar.xz"),
+            Some(ArchiveFormat::TarXz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_file_name("EGLL.tar.zst"),
+            Some(ArchiveFormat::TarZst)
+        );
+        assert_eq!(
+            ArchiveFormat::from_file_name("EGLL.7z"),
+            Some(ArchiveFormat::SevenZip)
+        );
+    }
+
+    #[test]
+    fn from_file_name_is_case_insensitive() {
+        assert_eq!(
+            ArchiveFormat::from_file_name("EGLL.ZIP"),
+            Some(ArchiveFormat::Zip)
+        );
+    }
+
+    #[test]
+    fn from_file_name_rejects_unknown_extensions() {
+        assert_eq!(ArchiveFormat::from_file_name("EGLL.rar"), None);
+        assert_eq!(ArchiveFormat::from_file_name("EGLL"), None);
+    }
+}
+
+/// Extracts `archive_path` (of the given `format`) into `tmp_dir`, producing
+/// the same temp-dir layout regardless of container so the downstream
+/// copy/PRF/ASR/NavData logic doesn't need to know which one it came from.
+pub async fn extract_archive(
+    format: ArchiveFormat,
+    archive_path: &Path,
+    tmp_dir: &TempDir,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        ArchiveFormat::Zip => extract_zip(archive_path, tmp_dir),
+        ArchiveFormat::TarGz => extract_tar_gz(archive_path, tmp_dir).await,
+        ArchiveFormat::TarBz2 => extract_tar_bz2(archive_path, tmp_dir).await,
+        ArchiveFormat::TarXz => extract_tar_xz(archive_path, tmp_dir).await,
+        ArchiveFormat::TarZst => extract_tar_zst(archive_path, tmp_dir).await,
+        ArchiveFormat::SevenZip => extract_sevenz(archive_path, tmp_dir),
+    }
+}
+
+fn extract_zip(archive_path: &Path, tmp_dir: &TempDir) -> Result<(), Box<dyn std::error::Error>> {
+    let file = File::open(archive_path)?;
+    let mut archive = ZipArchive::new(file)?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let outpath = match file.enclosed_name() {
+            Some(path) => tmp_dir.path().join(path.to_owned()),
+            None => continue,
+        };
+        if (*file.name()).ends_with('/') {
+            fs::create_dir_all(&outpath)?;
+        } else {
+            if let Some(p) = outpath.parent() {
+                fs::create_dir_all(p)?;
+            }
+            let mut outfile = File::create(&outpath)?;
+            copy(&mut file, &mut outfile)?;
+        }
+    }
+    Ok(())
+}
+
+async fn tar_reader(
+    archive_path: &Path,
+) -> Result<TokioBufReader<tokio::fs::File>, Box<dyn std::error::Error>> {
+    Ok(TokioBufReader::new(tokio::fs::File::open(archive_path).await?))
+}
+
+async fn extract_tar_gz(
+    archive_path: &Path,
+    tmp_dir: &TempDir,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let reader = GzipDecoder::new(tar_reader(archive_path).await?);
+    TarArchive::new(reader).unpack(tmp_dir.path()).await?;
+    Ok(())
+}
+
+async fn extract_tar_bz2(
+    archive_path: &Path,
+    tmp_dir: &TempDir,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let reader = BzDecoder::new(tar_reader(archive_path).await?);
+    TarArchive::new(reader).unpack(tmp_dir.path()).await?;
+    Ok(())
+}
+
+async fn extract_tar_xz(
+    archive_path: &Path,
+    tmp_dir: &TempDir,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let reader = XzDecoder::new(tar_reader(archive_path).await?);
+    TarArchive::new(reader).unpack(tmp_dir.path()).await?;
+    Ok(())
+}
+
+async fn extract_tar_zst(
+    archive_path: &Path,
+    tmp_dir: &TempDir,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let reader = ZstdDecoder::new(tar_reader(archive_path).await?);
+    TarArchive::new(reader).unpack(tmp_dir.path()).await?;
+    Ok(())
+}
+
+fn extract_sevenz(
+    archive_path: &Path,
+    tmp_dir: &TempDir,
+) -> Result<(), Box<dyn std::error::Error>> {
+    sevenz_rust::decompress_file(archive_path, tmp_dir.path())?;
+    Ok(())
+}
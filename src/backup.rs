@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BACKUP_ROOT: &str = "backup";
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// One file that was backed up before an in-place mutation, so a rollback
+/// knows whether to restore it or simply delete what we created.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BackupEntry {
+    /// Path relative to `es_path`; used both under the snapshot dir and to
+    /// locate the live file again on rollback.
+    relative_path: PathBuf,
+    existed_before: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    fir: String,
+    entries: Vec<BackupEntry>,
+}
+
+/// A single update run's backup session: every file about to be overwritten
+/// or edited is snapshotted here before the mutation happens, and the
+/// manifest is rewritten after each snapshot so it's readable even if the
+/// run never reaches `finish`.
+pub struct Backup {
+    fir: String,
+    dir: PathBuf,
+    entries: Vec<BackupEntry>,
+}
+
+impl Backup {
+    pub fn start(fir: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let dir = Path::new(BACKUP_ROOT).join(format!("{}-{}", fir, timestamp));
+        fs::create_dir_all(dir.join("files"))?;
+        Ok(Backup {
+            fir: fir.to_owned(),
+            dir,
+            entries: Vec::new(),
+        })
+    }
+
+    /// Snapshots `dest` (a path inside the live EuroScope install) before it
+    /// gets overwritten or edited.
+    pub fn snapshot(
+        &mut self,
+        es_path: &Path,
+        dest: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let relative_path = dest.strip_prefix(es_path).unwrap_or(dest).to_path_buf();
+        let existed_before = dest.exists();
+        if existed_before {
+            let snapshot_path = self.dir.join("files").join(&relative_path);
+            if let Some(parent) = snapshot_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(dest, &snapshot_path)?;
+        }
+        self.entries.push(BackupEntry {
+            relative_path,
+            existed_before,
+        });
+        // Write the manifest after every snapshot, not just once the whole
+        // run finishes, so a crash or a bad package mid-update still leaves
+        // a readable manifest behind for `rollback` to work from.
+        self.write_manifest()
+    }
+
+    fn write_manifest(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let manifest = Manifest {
+            fir: self.fir.clone(),
+            entries: self.entries.clone(),
+        };
+        let raw = serde_json::to_string_pretty(&manifest)?;
+        fs::write(self.dir.join(MANIFEST_FILE), raw)?;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_manifest()
+    }
+}
+
+/// Writes `contents` atomically: write to a temp file in the destination
+/// directory, then rename over the target, so a crash mid-write never
+/// leaves a half-written file in place.
+pub fn atomic_write(dest: &Path, contents: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let tmp_path = tmp_path_for(dest)?;
+    {
+        let mut tmp_file = fs::File::create(&tmp_path)?;
+        tmp_file.write_all(contents)?;
+    }
+    fs::rename(&tmp_path, dest)?;
+    Ok(())
+}
+
+/// Atomically copies `src` to `dest` via the same temp-file-then-rename
+/// pattern as `atomic_write`.
+pub fn atomic_copy(src: &Path, dest: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let tmp_path = tmp_path_for(dest)?;
+    fs::copy(src, &tmp_path)?;
+    fs::rename(&tmp_path, dest)?;
+    Ok(())
+}
+
+fn tmp_path_for(dest: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let parent = dest.parent().ok_or("destination has no parent directory")?;
+    fs::create_dir_all(parent)?;
+    let file_name = dest
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or("destination has no file name")?;
+    Ok(parent.join(format!(".{}.tmp", file_name)))
+}
+
+/// Finds the most recent backup snapshot for `fir` and restores every file
+/// it recorded: files that existed before the update are copied back,
+/// files the update created from nothing are deleted.
+pub fn rollback(fir: &str, es_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut candidates: Vec<PathBuf> = fs::read_dir(BACKUP_ROOT)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .map(|n| n.starts_with(&format!("{}-", fir)))
+                .unwrap_or(false)
+        })
+        .collect();
+    candidates.sort();
+    let latest = candidates
+        .pop()
+        .ok_or_else(|| format!("no backup found for FIR {}", fir))?;
+
+    let raw = fs::read_to_string(latest.join(MANIFEST_FILE))?;
+    let manifest: Manifest = serde_json::from_str(&raw)?;
+
+    println!("Rolling back {} to snapshot {}", fir, latest.display());
+    for entry in manifest.entries.iter().rev() {
+        let live_path = es_path.join(&entry.relative_path);
+        if entry.existed_before {
+            let snapshot_path = latest.join("files").join(&entry.relative_path);
+            println!("\tRestoring {}", entry.relative_path.display());
+            atomic_copy(&snapshot_path, &live_path)?;
+        } else if live_path.exists() {
+            println!("\tRemoving {}", entry.relative_path.display());
+            fs::remove_file(&live_path)?;
+        }
+    }
+    println!("Rollback complete");
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+const CACHE_FILE: &str = ".es-sector-cache.json";
+
+/// What we remember about the last successful check for a FIR's package,
+/// so the next run can ask aero-nav "has this changed?" instead of
+/// re-downloading blindly.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub file_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// SHA-256 of the downloaded archive, so a re-run can tell a corrupted
+    /// or partially-written `sector.zip` from a genuinely new package.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_sha256: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl CacheState {
+    pub fn load() -> Self {
+        match fs::read_to_string(CACHE_FILE) {
+            Ok(raw) => serde_json::from_str(&raw).unwrap_or_default(),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let raw = serde_json::to_string_pretty(self)?;
+        fs::write(CACHE_FILE, raw)?;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&CacheEntry> {
+        self.entries.get(key)
+    }
+
+    pub fn set(&mut self, key: &str, entry: CacheEntry) {
+        self.entries.insert(key.to_owned(), entry);
+    }
+
+    /// Drops the remembered state for `key`, so the next run re-checks the
+    /// listing page and re-downloads instead of trusting stale bookkeeping.
+    pub fn remove(&mut self, key: &str) {
+        self.entries.remove(key);
+    }
+}
+
+/// Key the cache by FIR + package name, since a single install can track
+/// several FIRs that each publish their own package.
+pub fn cache_key(fir: &str, package_name: &str) -> String {
+    format!("{}:{}", fir, package_name)
+}
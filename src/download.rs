@@ -0,0 +1,220 @@
+use futures_util::StreamExt;
+use reqwest::{header, Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Where a FIR's in-progress download is parked between runs, so a dropped
+/// connection doesn't force a restart from byte zero.
+pub fn part_path_for(fir: &str, package_name: &str) -> PathBuf {
+    PathBuf::from(format!(".es-sector-download-{}-{}.part", fir, package_name))
+}
+
+/// Records which resource a `.part` file belongs to, so a partial from an
+/// interrupted download of one archive is never resumed against a
+/// different URL (e.g. after an AIRAC bump) — the bytes would be appended
+/// onto the wrong content and produce a corrupt archive.
+#[derive(Serialize, Deserialize)]
+struct PartMeta {
+    file_url: String,
+}
+
+fn meta_path_for(part_path: &Path) -> PathBuf {
+    let mut name = part_path.as_os_str().to_owned();
+    name.push(".meta");
+    PathBuf::from(name)
+}
+
+fn discard_partial(part_path: &Path, meta_path: &Path) {
+    let _ = fs::remove_file(part_path);
+    let _ = fs::remove_file(meta_path);
+}
+
+pub struct DownloadOutcome {
+    pub not_modified: bool,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// Downloads `file_url` into `dest`, resuming from a `.part` file left by a
+/// previous interrupted run instead of starting over.
+///
+/// Also honours conditional-request caching: pass the previously recorded
+/// `ETag`/`Last-Modified` and a `304 Not Modified` response is surfaced
+/// without writing anything.
+pub async fn download_resumable(
+    client: &Client,
+    file_url: &str,
+    if_none_match: Option<&str>,
+    if_modified_since: Option<&str>,
+    part_path: &Path,
+    dest: &Path,
+) -> Result<DownloadOutcome, Box<dyn std::error::Error>> {
+    let meta_path = meta_path_for(part_path);
+    let mut existing_len = fs::metadata(part_path).map(|m| m.len()).unwrap_or(0);
+    if existing_len > 0 {
+        let belongs_to_this_url = fs::read_to_string(&meta_path)
+            .ok()
+            .and_then(|raw| serde_json::from_str::<PartMeta>(&raw).ok())
+            .map(|meta| meta.file_url == file_url)
+            .unwrap_or(false);
+        if !belongs_to_this_url {
+            println!(
+                "Partial download {} belongs to a different resource; discarding it",
+                part_path.display()
+            );
+            discard_partial(part_path, &meta_path);
+            existing_len = 0;
+        }
+    }
+
+    let mut request = client.get(file_url);
+    if let Some(etag) = if_none_match {
+        request = request.header(header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = if_modified_since {
+        request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+    }
+    if existing_len > 0 {
+        println!(
+            "Resuming download from byte {} ({})",
+            existing_len,
+            part_path.display()
+        );
+        request = request.header(header::RANGE, format!("bytes={}-", existing_len));
+    }
+    let response = request.send().await?;
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(DownloadOutcome {
+            not_modified: true,
+            etag: None,
+            last_modified: None,
+        });
+    }
+
+    let etag = header_value(&response, header::ETAG);
+    let last_modified = header_value(&response, header::LAST_MODIFIED);
+
+    let resuming = existing_len > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+    if existing_len > 0 && !resuming {
+        println!("Server did not honour the Range request; restarting download");
+        discard_partial(part_path, &meta_path);
+    }
+
+    let total_len = expected_total_len(&response, resuming);
+
+    // Record which resource this `.part` belongs to before writing any
+    // bytes, so a future run can tell whether it's safe to resume.
+    let meta_raw = serde_json::to_string(&PartMeta {
+        file_url: file_url.to_owned(),
+    })?;
+    fs::write(&meta_path, meta_raw)?;
+
+    // Stream chunk-by-chunk instead of buffering the whole body: if the
+    // connection drops mid-transfer, whatever was already streamed stays on
+    // disk in `part_path` for the next run to resume from, rather than the
+    // error propagating before a single byte is written.
+    let mut part_file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resuming)
+        .truncate(!resuming)
+        .open(part_path)?;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        part_file.write_all(&chunk?)?;
+    }
+    drop(part_file);
+
+    if let Some(total) = total_len {
+        let written = fs::metadata(part_path)?.len();
+        if written != total {
+            return Err(format!(
+                "downloaded {} bytes but expected {}; leaving {} in place to resume later",
+                written,
+                total,
+                part_path.display()
+            )
+            .into());
+        }
+    }
+
+    persist(part_path, dest)?;
+    let _ = fs::remove_file(&meta_path);
+
+    Ok(DownloadOutcome {
+        not_modified: false,
+        etag,
+        last_modified,
+    })
+}
+
+/// Moves `part_path` to `dest`, falling back to copy-then-remove when the two
+/// live on different filesystems (`part_path` is CWD-relative, `dest` is
+/// inside a system tempdir, often tmpfs) and a plain rename would fail with
+/// `EXDEV`.
+fn persist(part_path: &Path, dest: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if fs::rename(part_path, dest).is_ok() {
+        return Ok(());
+    }
+    fs::copy(part_path, dest)?;
+    fs::remove_file(part_path)?;
+    Ok(())
+}
+
+fn header_value(response: &Response, name: header::HeaderName) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_owned())
+}
+
+fn expected_total_len(response: &Response, resuming: bool) -> Option<u64> {
+    expected_total_len_from_headers(response.headers(), resuming)
+}
+
+fn expected_total_len_from_headers(headers: &header::HeaderMap, resuming: bool) -> Option<u64> {
+    if resuming {
+        // "Content-Range: bytes start-end/total"
+        headers
+            .get(header::CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit('/').next())
+            .and_then(|v| v.parse::<u64>().ok())
+    } else {
+        headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expected_total_len_reads_content_range_when_resuming() {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(header::CONTENT_RANGE, "bytes 1000-1999/2000".parse().unwrap());
+        headers.insert(header::CONTENT_LENGTH, "1000".parse().unwrap());
+        assert_eq!(expected_total_len_from_headers(&headers, true), Some(2000));
+    }
+
+    #[test]
+    fn expected_total_len_reads_content_length_when_not_resuming() {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(header::CONTENT_LENGTH, "2000".parse().unwrap());
+        assert_eq!(expected_total_len_from_headers(&headers, false), Some(2000));
+    }
+
+    #[test]
+    fn expected_total_len_is_none_without_the_relevant_header() {
+        let headers = header::HeaderMap::new();
+        assert_eq!(expected_total_len_from_headers(&headers, true), None);
+        assert_eq!(expected_total_len_from_headers(&headers, false), None);
+    }
+}
@@ -1,12 +1,22 @@
+mod archive;
+mod backup;
+mod cache;
+mod download;
+mod manifest;
+mod patterns;
+
+use archive::ArchiveFormat;
+use backup::Backup;
+use cache::{CacheEntry, CacheState};
+use download::download_resumable;
+use manifest::InstalledManifest;
+use patterns::CopyFilter;
 use regex::Regex;
 use reqwest::{header, header::HeaderMap, redirect};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::fs::{File, OpenOptions};
-use std::io::{copy, Cursor, Write};
 use std::path::{Path, PathBuf};
-use tempfile::{Builder, TempDir};
-use zip::ZipArchive;
+use tempfile::Builder;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -18,6 +28,10 @@ struct Config {
     asr_path: String,
     navdata_path: String,
     prf_prefix: String,
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,6 +49,10 @@ fn read_config(file: &str) -> Vec<Config> {
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("ES Sector Update version {}", VERSION);
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(fir) = parse_rollback_arg(&args) {
+        return run_rollback(&fir);
+    }
     // load the values from config file
     let cfg = read_config("config.json");
     for config in cfg {
@@ -43,6 +61,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+fn parse_rollback_arg(args: &[String]) -> Option<String> {
+    let pos = args.iter().position(|a| a == "--rollback")?;
+    args.get(pos + 1).cloned()
+}
+
+fn run_rollback(fir: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let cfg = read_config("config.json");
+    let config = cfg
+        .into_iter()
+        .find(|c| c.fir == fir)
+        .ok_or_else(|| format!("no config entry for FIR {}", fir))?;
+    backup::rollback(fir, Path::new(config.es_path.as_str()))?;
+    // The backup only restores files; without this the cache still
+    // remembers the bad package's file_url/archive_sha256, so the very next
+    // normal run hits the "up to date" short-circuit and does nothing.
+    let mut cache = CacheState::load();
+    cache.remove(&cache::cache_key(fir, config.package_name.as_str()));
+    cache.save()?;
+    Ok(())
+}
+
 async fn work_fir(cfg: Config) -> Result<(), Box<dyn std::error::Error>> {
     let fir = cfg.fir.as_str();
     println!("-- FIR {} --", fir);
@@ -50,8 +89,17 @@ async fn work_fir(cfg: Config) -> Result<(), Box<dyn std::error::Error>> {
     let es_path = Path::new(cfg.es_path.as_str());
     
     let prf_prefix = cfg.prf_prefix.as_str();
+    let filter = CopyFilter::build(&cfg.include, &cfg.exclude)?;
     // Get latest download link from GNG
     let file_url = get_sector_link(fir, package_name).await?;
+    let mut cache = CacheState::load();
+    let cache_key = cache::cache_key(fir, package_name);
+    if let Some(cached) = cache.get(&cache_key) {
+        if cached.file_url == file_url {
+            println!("up to date");
+            return Ok(());
+        }
+    }
     // Create a temporary directory to hold the files
     let tmp_dir = Builder::new().prefix("es-sector-updater-").tempdir()?;
     // Configure the client to download the sector archive
@@ -61,38 +109,135 @@ async fn work_fir(cfg: Config) -> Result<(), Box<dyn std::error::Error>> {
         .redirect(redirect_policy)
         .default_headers(hdr)
         .build()?;
-    let response = client.get(file_url).send().await?;
-    let file_name = tmp_dir.path().join("sector.zip");
+    let (if_none_match, if_modified_since) = match cache.get(&cache_key) {
+        Some(cached) => (
+            cached.etag.as_deref().filter(|v| !v.is_empty()),
+            cached.last_modified.as_deref().filter(|v| !v.is_empty()),
+        ),
+        None => (None, None),
+    };
+    let format = ArchiveFormat::from_file_name(&file_url).ok_or("unrecognized archive format")?;
+    let file_name = tmp_dir.path().join(format!("sector.{}", format.extension()));
+    let part_path = download::part_path_for(fir, package_name);
     println!("Creating file: {}", file_name.display());
-    let mut file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(file_name.to_owned())?;
-    let mut content = Cursor::new(response.bytes().await?);
-    copy(&mut content, &mut file)?;
-    // File is now downloaded and closed; Time to unzip it
-    let archive = zip::ZipArchive::new(file)?;
-    unzip_archive(archive, &tmp_dir).await?;
-    // Archive is unzipped. No longer needed. Deleting it to make it easier to copy all files.
+    let outcome = download_resumable(
+        &client,
+        &file_url,
+        if_none_match,
+        if_modified_since,
+        &part_path,
+        &file_name,
+    )
+    .await?;
+    if outcome.not_modified {
+        println!("up to date");
+        return Ok(());
+    }
+    let etag = outcome.etag;
+    let last_modified = outcome.last_modified;
+    // Verify the archive landed intact before wasting time extracting it: if
+    // it hashes the same as the last archive we successfully installed, the
+    // package is unchanged (even under a new URL) and extraction is skipped.
+    let archive_sha256 = manifest::sha256_file(&file_name)?;
+    println!("SHA-256: {}", archive_sha256);
+    if cache.get(&cache_key).and_then(|c| c.archive_sha256.as_deref()) == Some(archive_sha256.as_str())
+    {
+        println!("Archive content unchanged since last install; skipping extraction");
+        fs::remove_file(file_name)?;
+        cache.set(
+            &cache_key,
+            CacheEntry {
+                file_url,
+                etag,
+                last_modified,
+                archive_sha256: Some(archive_sha256),
+            },
+        );
+        cache.save()?;
+        return Ok(());
+    }
+    archive::extract_archive(format, &file_name, &tmp_dir).await?;
+    // Archive is extracted. No longer needed. Deleting it to make it easier to copy all files.
     fs::remove_file(file_name)?;
     let tmp_path = tmp_dir.into_path();
-    copy_files(es_path, tmp_path.clone()).await?;
+    // From here on we're mutating the live ES install; snapshot everything
+    // we touch first so a bad package can be rolled back with --rollback.
+    let mut backup = Backup::start(fir)?;
+    let previous_manifest = InstalledManifest::load(es_path);
+    let mut new_manifest = InstalledManifest::default();
+    copy_files(
+        es_path,
+        tmp_path.clone(),
+        &mut backup,
+        &previous_manifest,
+        &mut new_manifest,
+        &filter,
+    )
+    .await?;
     // Set PRF sector files
     let sector_file_name = get_sector_file_name(&tmp_path).unwrap();
-    change_prf_sectors(es_path, sector_file_name, prf_prefix).await?;
+    change_prf_sectors(es_path, sector_file_name, prf_prefix, &mut backup, &filter).await?;
     // Clear ASRs from sector definitions
-    let asr_partial = es_path.join(cfg.asr_path);
+    let asr_rel_root = cfg.asr_path.clone();
+    let asr_partial = es_path.join(&cfg.asr_path);
     let asr_path = Path::new(&asr_partial);
-    clear_asr(asr_path.to_path_buf()).await?;
+    clear_asr(
+        asr_path.to_path_buf(),
+        es_path,
+        &asr_rel_root,
+        &mut backup,
+        &filter,
+    )
+    .await?;
     // Copy NavData
-    let navdata_path = tmp_path.join(cfg.navdata_path);
-    copy_navdata(es_path, navdata_path).await?;
+    let navdata_rel_root = cfg.navdata_path.clone();
+    let navdata_path = tmp_path.join(&cfg.navdata_path);
+    copy_navdata(
+        es_path,
+        navdata_path,
+        &navdata_rel_root,
+        &mut backup,
+        &previous_manifest,
+        &mut new_manifest,
+        &filter,
+    )
+    .await?;
+    // Delete files the previous package shipped that the new one dropped.
+    // Files an exclude rule protects are skipped here too, otherwise a
+    // controller's personal file never gets recorded into new_manifest and
+    // would be wrongly deleted as "stale" on every subsequent update.
+    for stale in previous_manifest.stale_against(&new_manifest) {
+        if !filter.allows(Path::new(stale)) {
+            continue;
+        }
+        let stale_path = es_path.join(stale);
+        if stale_path.exists() {
+            println!("Removing stale file no longer shipped: {}", stale);
+            backup.snapshot(es_path, &stale_path)?;
+            fs::remove_file(&stale_path)?;
+        }
+    }
+    // Snapshot the old installed-manifest before overwriting it so a later
+    // `--rollback` restores it alongside the files it describes.
+    backup.snapshot(es_path, &manifest::manifest_path(es_path))?;
+    new_manifest.save(es_path)?;
+    backup.finish()?;
+    cache.set(
+        &cache_key,
+        CacheEntry {
+            file_url,
+            etag,
+            last_modified,
+            archive_sha256: Some(archive_sha256),
+        },
+    );
+    cache.save()?;
     Ok(())
 }
 
-fn is_correct_link(link: &str, package_name: &str, format: &str) -> bool {
-    return link.contains(package_name) && link.ends_with(format);
+fn is_correct_link(link: &str, package_name: &str, formats: &[&str]) -> bool {
+    let lower = link.to_lowercase();
+    return link.contains(package_name) && formats.iter().any(|format| lower.ends_with(format));
 }
 
 async fn get_sector_link(fir: &str, package_name: &str) -> Result<String, Box<dyn std::error::Error>> {
@@ -101,10 +246,11 @@ async fn get_sector_link(fir: &str, package_name: &str) -> Result<String, Box<dy
     let website = reqwest::get(url).await?.text().await?;
     let document = scraper::Html::parse_document(&website);
     let link_selector = scraper::Selector::parse("td>a").unwrap();
+    let formats = ArchiveFormat::extensions();
     let links = document
         .select(&link_selector)
         .map(|x| x.value().attr("href").unwrap())
-        .filter(|x| is_correct_link(x, package_name, "zip"));
+        .filter(|x| is_correct_link(x, package_name, formats));
     let file_url = links.last().unwrap().to_owned();
     println!("Got url: {}", file_url);
     return Ok(file_url);
@@ -165,32 +311,14 @@ fn set_headers() -> HeaderMap {
     return hdr
 }
 
-async fn unzip_archive(
-    mut archive: ZipArchive<File>,
-    tmp_dir: &TempDir,
+async fn copy_files(
+    es_path: &Path,
+    tmp_path: PathBuf,
+    backup: &mut Backup,
+    previous_manifest: &InstalledManifest,
+    new_manifest: &mut InstalledManifest,
+    filter: &CopyFilter,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        let outpath = match file.enclosed_name() {
-            Some(path) => tmp_dir.path().join(path.to_owned()),
-            None => continue,
-        };
-        if (*file.name()).ends_with('/') {
-            fs::create_dir_all(&outpath).unwrap();
-        } else {
-            if let Some(p) = outpath.parent() {
-                if !p.exists() {
-                    fs::create_dir_all(p).unwrap();
-                }
-                let mut outfile = File::create(&outpath).unwrap();
-                copy(&mut file, &mut outfile).unwrap();
-            }
-        }
-    }
-    Ok(())
-}
-
-async fn copy_files(es_path: &Path, tmp_path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
     println! {"Copying files to ES dir"};
     // Copy all files to Euroscope directory
     for entry in fs::read_dir(&tmp_path)? {
@@ -200,14 +328,46 @@ async fn copy_files(es_path: &Path, tmp_path: PathBuf) -> Result<(), Box<dyn std
         if ftyp.is_dir() {
             fs::create_dir_all(&dest).unwrap();
         } else {
-            if let Some(p) = dest.parent() {
-                if !p.exists() {
-                    fs::create_dir_all(p).unwrap();
-                }
-                let mut file = File::open(entry.path())?;
-                let mut dest_file = File::create(&dest)?;
-                copy(&mut file, &mut dest_file)?;
+            let relative_path = relative_path_string(es_path, &dest);
+            if !filter.allows(Path::new(&relative_path)) {
+                println!("Skipping excluded file: {}", relative_path);
+                continue;
             }
+            warn_if_modified(&dest, &relative_path, previous_manifest)?;
+            backup.snapshot(es_path, &dest)?;
+            let sha256 = manifest::sha256_file(&entry.path())?;
+            backup::atomic_copy(&entry.path(), &dest)?;
+            new_manifest.record(relative_path, sha256);
+        }
+    }
+    Ok(())
+}
+
+fn relative_path_string(es_path: &Path, dest: &Path) -> String {
+    dest.strip_prefix(es_path)
+        .unwrap_or(dest)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Warns (without blocking the update) when a file we're about to overwrite
+/// no longer matches the hash we recorded for it last time, which means a
+/// controller has customized it locally since.
+fn warn_if_modified(
+    dest: &Path,
+    relative_path: &str,
+    previous_manifest: &InstalledManifest,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !dest.exists() {
+        return Ok(());
+    }
+    if let Some(expected) = previous_manifest.hash_of(relative_path) {
+        let actual = manifest::sha256_file(dest)?;
+        if actual != expected {
+            println!(
+                "Warning: {} was modified locally since the last update; overwriting it",
+                relative_path
+            );
         }
     }
     Ok(())
@@ -230,6 +390,8 @@ async fn change_prf_sectors(
     es_path: &Path,
     sector_file_name: String,
     prf_prefix: &str,
+    backup: &mut Backup,
+    filter: &CopyFilter,
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("Changing sectorfile in PRFs");
     let prf_regex = Regex::new(r"Settings\tsector.*\n").unwrap();
@@ -238,41 +400,58 @@ async fn change_prf_sectors(
         let entry = entry?;
         let fname = entry.file_name().to_str().unwrap().to_owned();
         if fname.ends_with(".prf") && fname.starts_with(prf_prefix) {
+            if !filter.allows(Path::new(&fname)) {
+                println!("Skipping excluded file: {}", fname);
+                continue;
+            }
             println!("\t{}", fname);
             let contents = fs::read_to_string(entry.path())?;
             let new = prf_regex.replace_all(contents.as_str(), sector_string.to_owned());
-            let mut file = OpenOptions::new()
-                .write(true)
-                .truncate(true)
-                .open(entry.path())?;
-            file.write(new.as_bytes())?;
+            backup.snapshot(es_path, &entry.path())?;
+            backup::atomic_write(&entry.path(), new.as_bytes())?;
         }
     }
     Ok(())
 }
 
-async fn clear_asr(asr_path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+async fn clear_asr(
+    asr_path: PathBuf,
+    es_path: &Path,
+    asr_rel_root: &str,
+    backup: &mut Backup,
+    filter: &CopyFilter,
+) -> Result<(), Box<dyn std::error::Error>> {
     println!("Clearing ASRs");
     let asr_regex = Regex::new(r"SECTORFILE:.*\nSECTORTITLE:.*\n").unwrap();
     for entry in fs::read_dir(asr_path)? {
         let entry = entry?;
         let fname = entry.file_name().to_str().unwrap().to_owned();
         if fname.ends_with(".asr") {
+            let relative_path = Path::new(asr_rel_root).join(&fname);
+            if !filter.allows(&relative_path) {
+                println!("Skipping excluded file: {}", relative_path.display());
+                continue;
+            }
             // It's an ASR file. Delete the sector file binding.
             println!("\t{}", fname);
             let contents = fs::read_to_string(entry.path())?;
             let new = asr_regex.replace_all(contents.as_str(), "SECTORFILE:\nSECTORTITLE:\n");
-            let mut file = OpenOptions::new()
-                .write(true)
-                .truncate(true)
-                .open(entry.path())?;
-            file.write(new.as_bytes())?;
+            backup.snapshot(es_path, &entry.path())?;
+            backup::atomic_write(&entry.path(), new.as_bytes())?;
         }
     }
     Ok(())
 }
 
-async fn copy_navdata(es_path: &Path, tmp_navdata: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+async fn copy_navdata(
+    es_path: &Path,
+    tmp_navdata: PathBuf,
+    navdata_rel_root: &str,
+    backup: &mut Backup,
+    previous_manifest: &InstalledManifest,
+    new_manifest: &mut InstalledManifest,
+    filter: &CopyFilter,
+) -> Result<(), Box<dyn std::error::Error>> {
     println! {"Copying NavData to ES dir"};
     let es_navdata = es_path.join("NavData");
     // Copy all files to Euroscope directory
@@ -283,15 +462,21 @@ async fn copy_navdata(es_path: &Path, tmp_navdata: PathBuf) -> Result<(), Box<dy
         if ftyp.is_dir() {
             fs::create_dir_all(&dest).unwrap();
         } else {
-            if let Some(p) = dest.parent() {
-                if !p.exists() {
-                    fs::create_dir_all(p).unwrap();
-                }
-                println!("\t{}", entry.file_name().to_str().unwrap().to_owned());
-                let mut file = File::open(entry.path())?;
-                let mut dest_file = File::create(&dest)?;
-                copy(&mut file, &mut dest_file)?;
+            let relative_to_extraction_root = Path::new(navdata_rel_root).join(entry.file_name());
+            if !filter.allows(&relative_to_extraction_root) {
+                println!(
+                    "Skipping excluded file: {}",
+                    relative_to_extraction_root.display()
+                );
+                continue;
             }
+            println!("\t{}", entry.file_name().to_str().unwrap().to_owned());
+            let relative_path = relative_path_string(es_path, &dest);
+            warn_if_modified(&dest, &relative_path, previous_manifest)?;
+            backup.snapshot(es_path, &dest)?;
+            let sha256 = manifest::sha256_file(&entry.path())?;
+            backup::atomic_copy(&entry.path(), &dest)?;
+            new_manifest.record(relative_path, sha256);
         }
     }
     Ok(())
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+const MANIFEST_FILE: &str = "installed-manifest.json";
+
+/// Every file the updater has written into the ES directory, keyed by its
+/// path relative to `es_path`, mapped to the SHA-256 of what we wrote.
+///
+/// Diffing the previous run's manifest against the current one is what
+/// lets us warn about locally-modified files before clobbering them, and
+/// delete files the new package dropped that the old one shipped.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct InstalledManifest {
+    files: HashMap<String, String>,
+}
+
+/// Where `InstalledManifest` lives inside an ES install, exposed so callers
+/// that need to back it up (e.g. before a rollback overwrite) don't have to
+/// duplicate the file name.
+pub fn manifest_path(es_path: &Path) -> PathBuf {
+    es_path.join(MANIFEST_FILE)
+}
+
+impl InstalledManifest {
+    pub fn load(es_path: &Path) -> Self {
+        match fs::read_to_string(es_path.join(MANIFEST_FILE)) {
+            Ok(raw) => serde_json::from_str(&raw).unwrap_or_default(),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self, es_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let raw = serde_json::to_string_pretty(self)?;
+        fs::write(es_path.join(MANIFEST_FILE), raw)?;
+        Ok(())
+    }
+
+    pub fn hash_of(&self, relative_path: &str) -> Option<&str> {
+        self.files.get(relative_path).map(|v| v.as_str())
+    }
+
+    pub fn record(&mut self, relative_path: String, sha256: String) {
+        self.files.insert(relative_path, sha256);
+    }
+
+    /// Paths this manifest knows about that `current` doesn't, i.e. files
+    /// the previous package shipped but the new one no longer does.
+    pub fn stale_against<'a>(&'a self, current: &'a InstalledManifest) -> Vec<&'a str> {
+        self.files
+            .keys()
+            .filter(|path| !current.files.contains_key(*path))
+            .map(|path| path.as_str())
+            .collect()
+    }
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_against_finds_dropped_files_only() {
+        let mut previous = InstalledManifest::default();
+        previous.record("Settings/kept.sct".to_string(), "aaa".to_string());
+        previous.record("Settings/dropped.sct".to_string(), "bbb".to_string());
+
+        let mut current = InstalledManifest::default();
+        current.record("Settings/kept.sct".to_string(), "ccc".to_string());
+        current.record("Settings/new.sct".to_string(), "ddd".to_string());
+
+        let stale = previous.stale_against(&current);
+        assert_eq!(stale, vec!["Settings/dropped.sct"]);
+    }
+
+    #[test]
+    fn stale_against_empty_when_everything_still_shipped() {
+        let mut previous = InstalledManifest::default();
+        previous.record("Settings/kept.sct".to_string(), "aaa".to_string());
+
+        let mut current = InstalledManifest::default();
+        current.record("Settings/kept.sct".to_string(), "zzz".to_string());
+
+        assert!(previous.stale_against(&current).is_empty());
+    }
+}
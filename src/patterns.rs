@@ -0,0 +1,90 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::Path;
+
+/// Gitignore-style include/exclude rules, evaluated against a path relative
+/// to the extraction root, so a vACC can ship a standard package while
+/// letting individual controllers protect personal files from being
+/// overwritten.
+pub struct CopyFilter {
+    include: Option<Gitignore>,
+    exclude: Gitignore,
+}
+
+impl CopyFilter {
+    pub fn build(
+        include: &[String],
+        exclude: &[String],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(build_matcher(include)?)
+        };
+        let exclude = build_matcher(exclude)?;
+        Ok(CopyFilter { include, exclude })
+    }
+
+    /// Whether `relative_path` should be copied / operated on.
+    pub fn allows(&self, relative_path: &Path) -> bool {
+        if self.exclude.matched(relative_path, false).is_ignore() {
+            return false;
+        }
+        match &self.include {
+            Some(include) => include.matched(relative_path, false).is_ignore(),
+            None => true,
+        }
+    }
+}
+
+fn build_matcher(patterns: &[String]) -> Result<Gitignore, Box<dyn std::error::Error>> {
+    let mut builder = GitignoreBuilder::new("");
+    for pattern in patterns {
+        builder.add_line(None, pattern)?;
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn allows_everything_with_no_rules() {
+        let filter = CopyFilter::build(&[], &[]).unwrap();
+        assert!(filter.allows(Path::new("NavData/anything.dat")));
+    }
+
+    #[test]
+    fn exclude_rule_blocks_matching_path() {
+        let filter = CopyFilter::build(&[], &strings(&["*.asr"])).unwrap();
+        assert!(!filter.allows(Path::new("Settings/mine.asr")));
+        assert!(filter.allows(Path::new("Settings/mine.sct")));
+    }
+
+    #[test]
+    fn include_rule_restricts_to_matching_path() {
+        let filter = CopyFilter::build(&strings(&["NavData/**"]), &[]).unwrap();
+        assert!(filter.allows(Path::new("NavData/fixes.dat")));
+        assert!(!filter.allows(Path::new("Settings/mine.sct")));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let filter =
+            CopyFilter::build(&strings(&["NavData/**"]), &strings(&["NavData/local.dat"]))
+                .unwrap();
+        assert!(!filter.allows(Path::new("NavData/local.dat")));
+        assert!(filter.allows(Path::new("NavData/fixes.dat")));
+    }
+
+    #[test]
+    fn negated_exclude_pattern_carves_out_an_allowance() {
+        let filter = CopyFilter::build(&[], &strings(&["*.asr", "!keep.asr"])).unwrap();
+        assert!(!filter.allows(Path::new("drop.asr")));
+        assert!(filter.allows(Path::new("keep.asr")));
+    }
+}